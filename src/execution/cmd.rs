@@ -1,40 +1,133 @@
 use std::{
-    fs::metadata,
+    collections::{HashMap, HashSet, VecDeque},
+    fs::{self, metadata},
     path::PathBuf,
     time::SystemTime,
+    sync::{Condvar, Mutex},
+    thread,
     process::{
         exit,
         Command
     },
 };
 
+// A recipe parameter declared on a job's target line, e.g. the `env` in `deploy env="staging":`.
+pub struct Param {
+    name: String,
+    default: Option::<String>,
+}
+
+impl Param {
+    #[inline]
+    pub fn new(name: String, default: Option::<String>) -> Self {
+        Self { name, default }
+    }
+}
+
+// One line of a recipe body: its command tokens, and whether it was prefixed with `@` (quiet,
+// i.e. not echoed before it runs) with the `@` itself already stripped off by the lexer.
+pub struct BodyLine {
+    quiet: bool,
+    cmd: Vec::<String>,
+}
+
+impl BodyLine {
+    #[inline]
+    pub fn new(quiet: bool, cmd: Vec::<String>) -> Self {
+        Self { quiet, cmd }
+    }
+}
+
 pub struct Job {
     target: String,
     dependencies: Vec::<String>,
-    body: Vec::<Vec::<String>>
+    body: Vec::<BodyLine>,
+    params: Vec::<Param>,
 }
 
 impl Job {
     #[inline]
     pub fn new(target: String,
                dependencies: Vec::<String>,
-               body: Vec::<Vec::<String>>)
+               body: Vec::<BodyLine>,
+               params: Vec::<Param>)
         -> Self
     {
-        Self { target, dependencies, body }
+        Self { target, dependencies, body, params }
+    }
+
+    #[inline]
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+
+    // Binds `args` (each either `name=value` or, for whichever parameters aren't named that
+    // way, taken positionally in declaration order) to this job's parameters, falling back to
+    // each parameter's own default. Exits with an error if a parameter without a default is
+    // left unbound.
+    fn bind_params(&self, args: &[String]) -> HashMap::<String, String> {
+        let mut bindings = HashMap::with_capacity(self.params.len());
+        let mut positional = args.iter().filter(|a| !a.contains('='));
+
+        for param in &self.params {
+            let named = args.iter().find_map(|a| a.strip_prefix(&format!("{}=", param.name)));
+
+            let value = if let Some(value) = named {
+                value.to_string()
+            } else if let Some(value) = positional.next() {
+                value.clone()
+            } else if let Some(default) = &param.default {
+                default.clone()
+            } else {
+                eprintln!("[ERROR] Parameter `{}` of `{}` has no default and was not given a value",
+                    param.name, self.target);
+                exit(1);
+            };
+
+            bindings.insert(param.name.clone(), value);
+        }
+
+        bindings
     }
 }
 
 pub type Jobs = Vec::<Job>;
 
+// Mutable state shared by the worker pool in `Execute::execute_job_if_needed`, guarded by a
+// single mutex and woken up through a condvar as jobs finish and new ones become ready.
+struct SchedulerState {
+    ready: VecDeque::<usize>,
+    in_degree: Vec::<usize>,
+    remaining: usize,
+    failed: Option::<i32>,
+}
+
 pub struct Execute {
     jobs: Jobs,
+    jobs_limit: usize,
+    quiet: bool,
 }
 
 impl Execute {
     #[inline]
     pub fn new(jobs: Jobs) -> Self {
-        Self { jobs }
+        let jobs_limit = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+        Self { jobs, jobs_limit, quiet: false }
+    }
+
+    // Corresponds to the `-j N` command line flag. Defaults to the available parallelism.
+    #[inline]
+    pub fn with_jobs_limit(mut self, n: usize) -> Self {
+        self.jobs_limit = n.max(1);
+        self
+    }
+
+    // Forces every body line to run silently, regardless of its own `@` prefix. Corresponds to
+    // a global `-q`/`--quiet` flag.
+    #[inline]
+    pub fn with_quiet(mut self, quiet: bool) -> Self {
+        self.quiet = quiet;
+        self
     }
 
     #[inline]
@@ -43,35 +136,72 @@ impl Execute {
         p.exists()
     }
 
-    #[inline]
-    fn get_last_modification_time(s: &str) -> std::io::Result::<SystemTime> {
+    fn get_last_modification_time(&self, s: &str) -> std::io::Result::<SystemTime> {
         metadata::<PathBuf>(s.into()).map_err(|err| {
             eprintln!("[ERROR] Failed to get last modification time of \"{s}\", apparently it does not exist");
+            if let Some(similar) = self.suggest(s) {
+                eprintln!("Did you mean `{similar}`?");
+            }
             err
         })?.modified()
     }
 
+    // Classic DP Levenshtein distance: for strings a,b build a (|a|+1)x(|b|+1) table where
+    // cell[i][j] = min(delete, insert, substitute-with-cost-0-if-equal-else-1), keeping only
+    // two rolling rows since we never need the full table.
+    fn levenshtein(a: &str, b: &str) -> usize {
+        let (a, b) = if a.chars().count() <= b.chars().count() { (a, b) } else { (b, a) };
+        let a: Vec::<char> = a.chars().collect();
+        let b: Vec::<char> = b.chars().collect();
+
+        let mut prev: Vec::<usize> = (0..=a.len()).collect();
+        let mut curr = vec![0usize; a.len() + 1];
+
+        for (j, &bc) in b.iter().enumerate() {
+            curr[0] = j + 1;
+            for (i, &ac) in a.iter().enumerate() {
+                let cost = if ac == bc { 0 } else { 1 };
+                curr[i + 1] = (prev[i + 1] + 1).min(curr[i] + 1).min(prev[i] + cost);
+            }
+            std::mem::swap(&mut prev, &mut curr);
+        }
+
+        prev[a.len()]
+    }
+
+    // Closest known job target to `name`, if any is within a sensible edit distance.
+    fn suggest(&self, name: &str) -> Option::<&str> {
+        let threshold = (name.len() / 3).max(2);
+        self.jobs.iter()
+            .map(|j| (j.target.as_str(), Self::levenshtein(name, &j.target)))
+            .filter(|&(_, d)| d <= threshold)
+            .min_by_key(|&(_, d)| d)
+            .map(|(target, _)| target)
+    }
+
     #[inline]
     fn nothing_to_do_for(what: &str) {
         println!("Nothing to do for \"{what}\"");
     }
 
-    fn needs_rebuild(&self, job: &Job) -> bool {
-        let times = job.dependencies.iter().fold(Vec::with_capacity(job.dependencies.len()),
-            |mut times, dep|
-        {
-            // If current job depends on other job, the other job will be executed, recursively.
-            if let Some(job) = self.jobs.iter().find(|j| j.target.eq(dep)) {
-                self.execute_job_if_needed(job);
-            } else {
-                times.push(Self::get_last_modification_time(dep).unwrap());
-            } times
-        });
+    #[inline]
+    fn is_job_target(&self, name: &str) -> bool {
+        self.jobs.iter().any(|j| j.target == name)
+    }
 
+    // By the time a job is picked off the ready queue every job it depends on has already been
+    // built, so a dependency that's itself a job target has already done whatever rebuilding it
+    // needed; only plain-file dependencies (the common phony-target case like `build`/`all`
+    // that never write a same-named file) are compared against the target's mtime here.
+    fn needs_rebuild(&self, job: &Job) -> bool {
         if !Self::path_exists(&job.target) { return true }
 
-        let target_mod_time = Self::get_last_modification_time(&job.target).unwrap();
-        times.into_iter().any(|dep_mod_time| dep_mod_time > target_mod_time)
+        let target_mod_time = self.get_last_modification_time(&job.target).unwrap();
+        job.dependencies.iter()
+            .filter(|dep| !self.is_job_target(dep))
+            .any(|dep| {
+                self.get_last_modification_time(dep).unwrap() > target_mod_time
+            })
     }
 
     #[inline]
@@ -82,40 +212,286 @@ impl Execute {
     pub const CMD_ARG:  &'static str = if cfg!(windows) {"cmd"} else {"sh"};
     pub const CMD_ARG2: &'static str = if cfg!(windows) {"/C"} else {"-c"};
 
-    fn execute_job_if_needed(&self, job: &Job) {
-        if self.needs_rebuild(&job) {
-            for line in job.body.iter() {
-                let rendered = Self::render_cmd(line);
+    // Substitutes `$param` references in a body line with their bound value, leaving any token
+    // that isn't a bound parameter untouched.
+    fn substitute(line: &Vec::<String>, bindings: &HashMap::<String, String>) -> Vec::<String> {
+        line.iter().map(|token| {
+            token.strip_prefix('$')
+                .and_then(|name| bindings.get(name))
+                .cloned()
+                .unwrap_or_else(|| token.clone())
+        }).collect()
+    }
+
+    // Runs the recipe body for a single job, printing each command's captured output once that
+    // command has finished (its stdout goes to stderr). Returns the exit code
+    // of the first failing command, or 0 if every command succeeded.
+    fn run_body(&self, job: &Job, bindings: &HashMap::<String, String>) -> i32 {
+        if let Some(first) = job.body.first() {
+            let rendered = Self::render_cmd(&Self::substitute(&first.cmd, bindings));
+            if rendered.starts_with("#!") {
+                return self.run_shebang_body(job, bindings);
+            }
+        }
+
+        for line in job.body.iter() {
+            let cmd = Self::substitute(&line.cmd, bindings);
+            let rendered = Self::render_cmd(&cmd);
+
+            if !(self.quiet || line.quiet) {
                 println!("{rendered}");
+            }
 
-                let out = Command::new(Self::CMD_ARG).arg(Self::CMD_ARG2)
-                    .arg(rendered)
-                    .output()
-                    .expect("Failed to execute process");
+            let out = Command::new(Self::CMD_ARG).arg(Self::CMD_ARG2)
+                .arg(&rendered)
+                .output()
+                .expect("Failed to execute process");
 
-                if let Some(code) = out.status.code() {
-                    if code != 0 {
-                        if !out.stderr.is_empty() {
-                            eprint!("{stderr}", stderr = String::from_utf8_lossy(&out.stderr));
-                        }
+            if let Some(code) = out.status.code() {
+                if code != 0 {
+                    if !out.stderr.is_empty() {
+                        eprint!("{stderr}", stderr = String::from_utf8_lossy(&out.stderr));
+                    }
+
+                    eprintln!("Process exited abnormally with code {code}");
+                    return code;
+                }
+            }
+
+            if !out.stdout.is_empty() {
+                eprint!("{stdout}", stdout = String::from_utf8_lossy(&out.stdout));
+            }
+        }
 
-                        eprintln!("Process exited abnormally with code {code}");
-                        exit(1);
+        0
+    }
+
+    // Runs a recipe whose first body line is a `#!` shebang: the whole body is the script, not a
+    // sequence of shell commands, so it's written out to a single temp file, marked executable,
+    // and run directly instead of being shelled out to line by line.
+    fn run_shebang_body(&self, job: &Job, bindings: &HashMap::<String, String>) -> i32 {
+        let script = job.body.iter()
+            .map(|line| Self::render_cmd(&Self::substitute(&line.cmd, bindings)))
+            .collect::<Vec::<_>>()
+            .join("\n");
+
+        if !(self.quiet || job.body.iter().all(|line| line.quiet)) {
+            println!("{script}");
+        }
+
+        // `job.target` can contain `/` (a nested target like `dir/app`) or other characters
+        // that aren't valid in a single path component, so sanitize it before using it as part
+        // of the temp file's name rather than just joining it onto `temp_dir()` as-is.
+        let safe_target: String = job.target.chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+            .collect();
+        let path = std::env::temp_dir().join(format!("buildfile-{safe_target}-{}.sh", std::process::id()));
+        fs::write(&path, &script).expect("Failed to write shebang script");
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755))
+                .expect("Failed to mark shebang script executable");
+        }
+
+        let out = Command::new(&path).output();
+        let _ = fs::remove_file(&path);
+        let out = out.expect("Failed to execute shebang script");
+
+        if let Some(code) = out.status.code() {
+            if code != 0 {
+                if !out.stderr.is_empty() {
+                    eprint!("{stderr}", stderr = String::from_utf8_lossy(&out.stderr));
+                }
+
+                eprintln!("Process exited abnormally with code {code}");
+                return code;
+            }
+        }
+
+        if !out.stdout.is_empty() {
+            eprint!("{stdout}", stdout = String::from_utf8_lossy(&out.stdout));
+        }
+
+        0
+    }
+
+    #[inline]
+    fn job_index_by_target(&self) -> HashMap::<&str, usize> {
+        self.jobs.iter().enumerate().map(|(i, j)| (j.target.as_str(), i)).collect()
+    }
+
+    // Every job reachable from `root` through job-to-job dependencies, i.e. the subgraph that
+    // actually has to be scheduled to build `root`.
+    fn participating_jobs(&self, root: usize, by_target: &HashMap::<&str, usize>) -> HashSet::<usize> {
+        let mut seen = HashSet::new();
+        let mut stack = vec![root];
+        while let Some(idx) = stack.pop() {
+            if !seen.insert(idx) { continue }
+            for dep in &self.jobs[idx].dependencies {
+                if let Some(&di) = by_target.get(dep.as_str()) {
+                    stack.push(di);
+                }
+            }
+        }
+        seen
+    }
+
+    // `root_bindings` are the recipe-parameter values bound for `root` itself; every other job
+    // pulled in as a dependency runs with no bindings of its own (falling back to its own
+    // parameters' defaults, or erroring if it has none).
+    fn execute_job_if_needed(&self, root: &Job, root_bindings: &HashMap::<String, String>) {
+        let by_target = self.job_index_by_target();
+        let root_idx = by_target[root.target.as_str()];
+        let participating = self.participating_jobs(root_idx, &by_target);
+
+        let mut dependents: HashMap::<usize, Vec::<usize>> = HashMap::new();
+        let mut in_degree = vec![0usize; self.jobs.len()];
+        for &idx in &participating {
+            for dep in &self.jobs[idx].dependencies {
+                if let Some(&di) = by_target.get(dep.as_str()) {
+                    if participating.contains(&di) {
+                        in_degree[idx] += 1;
+                        dependents.entry(di).or_default().push(idx);
                     }
                 }
+            }
+        }
+
+        // Detect cycles up front: a Kahn's algorithm dry run that fails to visit every
+        // participating job means some of them only ever depend on each other.
+        {
+            let mut remaining_in_degree = in_degree.clone();
+            let mut queue: VecDeque::<usize> = participating.iter().copied()
+                .filter(|&i| remaining_in_degree[i] == 0)
+                .collect();
 
-                if !out.stdout.is_empty() {
-                    eprint!("{stdout}", stdout = String::from_utf8_lossy(&out.stdout));
+            let mut visited = 0;
+            while let Some(idx) = queue.pop_front() {
+                visited += 1;
+                if let Some(deps) = dependents.get(&idx) {
+                    for &d in deps {
+                        remaining_in_degree[d] -= 1;
+                        if remaining_in_degree[d] == 0 {
+                            queue.push_back(d);
+                        }
+                    }
                 }
             }
-        } else {
-            Self::nothing_to_do_for(&job.target);
+
+            if visited != participating.len() {
+                eprintln!("[ERROR] Dependency cycle detected among the jobs required to build \"{}\"", root.target);
+                exit(1);
+            }
+        }
+
+        let state = Mutex::new(SchedulerState {
+            ready: participating.iter().copied().filter(|&i| in_degree[i] == 0).collect(),
+            in_degree,
+            remaining: participating.len(),
+            failed: None,
+        });
+        let woken = Condvar::new();
+
+        thread::scope(|scope| {
+            for _ in 0..self.jobs_limit {
+                scope.spawn(|| loop {
+                    let idx = {
+                        let mut guard = state.lock().unwrap();
+                        loop {
+                            if guard.failed.is_some() || guard.remaining == 0 { return }
+                            if let Some(idx) = guard.ready.pop_front() { break idx }
+                            guard = woken.wait(guard).unwrap();
+                        }
+                    };
+
+                    let job = &self.jobs[idx];
+                    // A job pulled in as someone else's dependency gets no command line
+                    // arguments, so its parameters are bound here with none at all: each takes
+                    // its own default, and one without a default is an error, as in
+                    // `bind_params`.
+                    let own_bindings;
+                    let bindings: &HashMap::<String, String> = if idx == root_idx {
+                        root_bindings
+                    } else {
+                        own_bindings = job.bind_params(&[]);
+                        &own_bindings
+                    };
+                    if self.needs_rebuild(job) {
+                        let code = self.run_body(job, bindings);
+                        if code != 0 {
+                            let mut guard = state.lock().unwrap();
+                            guard.failed = Some(code);
+                            woken.notify_all();
+                            return;
+                        }
+                    } else {
+                        Self::nothing_to_do_for(&job.target);
+                    }
+
+                    let mut guard = state.lock().unwrap();
+                    guard.remaining -= 1;
+                    if let Some(deps) = dependents.get(&idx) {
+                        for &d in deps {
+                            guard.in_degree[d] -= 1;
+                            if guard.in_degree[d] == 0 {
+                                guard.ready.push_back(d);
+                            }
+                        }
+                    }
+                    woken.notify_all();
+                });
+            }
+        });
+
+        if let Some(code) = state.into_inner().unwrap().failed {
+            exit(code);
         }
     }
 
-    pub fn execute(&mut self) -> std::io::Result::<()> {
-        let job = self.jobs.first().unwrap_or_else(|| exit(0));
-        self.execute_job_if_needed(job);
+    // Builds every goal named on the command line, in order, falling back to the first
+    // declared job when no goals were given. None of these goals receive recipe arguments; use
+    // `execute_with_args` for a single parameterized goal.
+    pub fn execute(&mut self, goals: &[String]) -> std::io::Result::<()> {
+        let no_bindings = HashMap::new();
+
+        if goals.is_empty() {
+            let job = self.jobs.first().unwrap_or_else(|| exit(0));
+            self.execute_job_if_needed(job, &no_bindings);
+            return Ok(())
+        }
+
+        for goal in goals {
+            if let Some(job) = self.jobs.iter().find(|j| j.target.eq(goal)) {
+                self.execute_job_if_needed(job, &no_bindings);
+            } else if Self::path_exists(goal) {
+                Self::nothing_to_do_for(goal);
+            } else {
+                match self.suggest(goal) {
+                    Some(similar) => eprintln!("[ERROR] Nothing named `{goal}`. Did you mean `{similar}`?"),
+                    None => eprintln!("[ERROR] Nothing named `{goal}`"),
+                }
+                exit(1);
+            }
+        }
+
+        Ok(())
+    }
+
+    // Builds a single goal, binding `args` (`name=value` pairs, or bare values taken
+    // positionally) to its declared recipe parameters, e.g. `buildfile deploy env=prod`.
+    pub fn execute_with_args(&mut self, goal: &str, args: &[String]) -> std::io::Result::<()> {
+        let Some(job) = self.jobs.iter().find(|j| j.target.eq(goal)) else {
+            match self.suggest(goal) {
+                Some(similar) => eprintln!("[ERROR] Nothing named `{goal}`. Did you mean `{similar}`?"),
+                None => eprintln!("[ERROR] Nothing named `{goal}`"),
+            }
+            exit(1);
+        };
+
+        let bindings = job.bind_params(args);
+        self.execute_job_if_needed(job, &bindings);
         Ok(())
     }
 }
@@ -0,0 +1,31 @@
+use std::{
+    fs,
+    io,
+    path::Path,
+};
+
+// Owns the source text of every file pulled into a build. `Token`/`Tokens` borrow out of these
+// buffers, and an `include` can pull in a new file mid-parse, so each buffer is leaked for the
+// life of the process rather than being tied to a single `&str` handed to `Parser::new` up
+// front — the same `Box::leak` idiom `parser.rs` already uses for an included file's lexed
+// tokens, just applied to its raw source text here.
+//
+// Cycle detection (a file `include`ing itself, directly or transitively) is the caller's job,
+// not this one: `Parser` tracks which paths are currently on its include stack, since a file
+// that's merely been loaded before — a diamond where two unrelated files both `include` the
+// same third one — is completely legitimate and just needs re-reading, not rejecting.
+#[derive(Default)]
+pub struct Loader;
+
+impl Loader {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Reads `path` and returns a reference to its contents that lives for the rest of the
+    // program.
+    pub fn load(&self, path: &Path) -> io::Result::<&str> {
+        Ok(Box::leak(fs::read_to_string(path)?.into_boxed_str()))
+    }
+}
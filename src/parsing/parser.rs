@@ -2,20 +2,27 @@ use crate::{
     execution::cmd::Jobs,
     parsing::{
         ast::{
-            If, Ast, Decl, Expr, Item, Job, Operation
+            If, Ast, Decl, Expr, Item, Job, Operation, Param
         },
         lexer::{
-            LinizedTokens, Token, TokenType, Tokens
-        }
+            linize, LinizedTokens, Token, TokenType, Tokens
+        },
+        loader::Loader,
     },
 };
 
 use std::{
     fmt,
+    mem,
+    path::PathBuf,
     slice::Iter,
     iter::Peekable
 };
 
+const INCLUDES: &'static [&'static str] = &[
+    "include", "import"
+];
+
 pub type LinizedTokensIterator<'a> = Peekable::<Iter::<'a, (usize, Tokens<'a>)>>;
 
 const IFS: &'static [&'static str] = &[
@@ -27,6 +34,9 @@ pub enum ErrorType {
     UnexpectedToken,
     JobWithoutTarget,
     ExpectedOnlyOneTokenOnTheLeftSide,
+    FailedToInclude,
+    MissingRightHandSide,
+    IfMissingOperand,
 }
 
 impl fmt::Display for ErrorType {
@@ -36,128 +46,255 @@ impl fmt::Display for ErrorType {
             NoClosingEndif => write!(f, "No closing endif"),
             UnexpectedToken => write!(f, "Unexpected token"),
             JobWithoutTarget => write!(f, "Job without a target"),
-            ExpectedOnlyOneTokenOnTheLeftSide => write!(f, "Expected only one token on the left side")
+            ExpectedOnlyOneTokenOnTheLeftSide => write!(f, "Expected only one token on the left side"),
+            FailedToInclude => write!(f, "Failed to include file"),
+            MissingRightHandSide => write!(f, "Expected a right-hand side after the expression"),
+            IfMissingOperand => write!(f, "if is missing an operand"),
         }
     }
 }
 
+// A single recoverable parse error: what went wrong, where, and a rendering of the offending
+// line with a caret under the token that triggered it, so the user sees the exact spot without
+// needing to go re-read the file themselves.
 pub struct Error {
     ty: ErrorType,
-    note: Option::<&'static str>,
+    note: Option::<String>,
+    file: PathBuf,
+    line: usize,
+    column: usize,
+    snippet: String,
+    caret_offset: usize,
 }
 
 impl Error {
-    #[inline]
-    pub fn new(ty: ErrorType,
-               note: Option::<&'static str>)
-       -> Self
+    // `line` is the full run of tokens the offending token came from, `bad_idx` its position
+    // within that run; the column and caret offset are both derived from that position, per
+    // `parse_line`'s own token stream rather than the raw byte offsets in the source file.
+    fn at(ty: ErrorType,
+          note: Option::<String>,
+          file: PathBuf,
+          line_no: usize,
+          line: &Tokens,
+          bad_idx: usize)
+        -> Self
     {
-        Self { ty, note }
+        let snippet = line.iter().map(|t| t.str).collect::<Vec::<_>>().join(" ");
+        let caret_offset = line.iter().take(bad_idx).map(|t| t.str.len() + 1).sum();
+        Self { ty, note, file, line: line_no, column: bad_idx + 1, snippet, caret_offset }
     }
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let ty = &self.ty;
-        if let Some(note) = self.note {
-            write!(f, "{ty}\n\tNOTE: {note}")
-        } else {
-            write!(f, "{ty}")
+        writeln!(f, "{}:{}:{}: [ERROR] {}", self.file.display(), self.line, self.column, self.ty)?;
+        writeln!(f, "    {}", self.snippet)?;
+        writeln!(f, "    {}^", " ".repeat(self.caret_offset))?;
+        if let Some(note) = &self.note {
+            write!(f, "    NOTE: {note}")?;
         }
+        Ok(())
     }
 }
 
+// Either the line-by-line syntax errors collected while scanning, or the single structural
+// error `Ast::parse` reports once the items it was handed don't hang together (e.g. a
+// dependency on a target that was never declared).
+pub enum ParseError {
+    Syntax(Vec::<Error>),
+    Ast(crate::parsing::ast::Error),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Syntax(errors) => {
+                for (i, err) in errors.iter().enumerate() {
+                    if i > 0 { writeln!(f)?; }
+                    write!(f, "{err}")?;
+                }
+                Ok(())
+            }
+            ParseError::Ast(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+// One file's position in the include stack: its path (for error messages), the iterator over
+// its linized tokens, and how many lines of it we've consumed so far.
+struct Frame<'a> {
+    path: PathBuf,
+    iter: LinizedTokensIterator<'a>,
+    line_no: usize,
+}
+
 pub struct Parser<'a> {
     ast: Ast<'a>,
-    iter: LinizedTokensIterator<'a>,
-    err_token: Option::<&'a Token<'a>>,
+    // One frame per file currently being spliced in, innermost (the deepest `include`) last.
+    // Exhausting the top one pops back out to whichever file `include`d it.
+    frames: Vec::<Frame<'a>>,
+    loader: &'a Loader,
+    errors: Vec::<Error>,
+    // Canonical paths of every included file still on the include stack, innermost last. Only
+    // used to reject a file including itself (directly or transitively); a path that's been
+    // fully loaded and popped back off before is not on this stack anymore and is fine to load
+    // again.
+    active: Vec::<PathBuf>,
 }
 
 impl<'a> Parser<'a> {
     #[inline]
-    pub fn new(ts: &'a LinizedTokens<'a>) -> Self {
+    pub fn new(ts: &'a LinizedTokens<'a>, loader: &'a Loader, path: PathBuf) -> Self {
         Self {
             ast: Ast::default(),
-            iter: ts.into_iter().peekable(),
-            err_token: None
+            frames: vec![Frame { path, iter: ts.into_iter().peekable(), line_no: 0 }],
+            loader,
+            errors: Vec::new(),
+            active: Vec::new(),
         }
     }
 
-    #[track_caller]
-    fn report_err(&mut self, err: Error) -> ! {
-        if let Some(errt) = self.err_token {
-            panic!("{errt}: [ERROR] {err}")
-        } else {
-            panic!("[ERROR] {err}")
+    // Pulls the next line out of whichever included file is currently innermost, popping back
+    // out to the file that `include`d it once that one is exhausted.
+    fn next_line(&mut self) -> Option::<&'a (usize, Tokens<'a>)> {
+        loop {
+            let frame = self.frames.last_mut()?;
+            if let Some(line) = frame.iter.next() {
+                frame.line_no += 1;
+                return Some(line)
+            }
+            self.frames.pop();
+            // Every frame but the root one pushed a matching entry onto `active` when it was
+            // included; pop it back off now that the frame it guarded is exhausted.
+            self.active.pop();
         }
     }
 
-    // Unexpected First Token error
     #[inline]
-    #[track_caller]
-    fn uft_err(&mut self, line: &'a Tokens) -> ! {
-        self.err_token = line.get(0);
-        self.report_err(Error::new(ErrorType::UnexpectedToken, None))
-    }
-
-    // To check token that we have only one token on the left side in these kinda situations:
-    // ```
-    // FLAGS=-f 69
-    // ```
-    // or here:
-    // ```
-    // $OUT: main.c
-    //     $CC -o $t $FLAGS
-    // ```
+    fn peek_line(&mut self) -> Option::<&&'a (usize, Tokens<'a>)> {
+        self.frames.last_mut().and_then(|f| f.iter.peek())
+    }
+
+    #[inline]
+    fn current_file(&self) -> PathBuf {
+        self.frames.last().map_or_else(PathBuf::new, |f| f.path.clone())
+    }
+
+    // The directory a relative `include`/`import` path on the current line should be resolved
+    // against: the directory of whichever file is currently innermost, not the process's CWD,
+    // so a file reached via `include` can itself `include` siblings by relative path.
+    #[inline]
+    fn current_dir(&self) -> PathBuf {
+        self.current_file().parent().map_or_else(PathBuf::new, PathBuf::from)
+    }
+
+    #[inline]
+    fn current_line_no(&self) -> usize {
+        self.frames.last().map_or(0, |f| f.line_no)
+    }
+
+    // Records a recoverable error and lets the caller carry on parsing the rest of the file, so
+    // a single run reports every mistake instead of just the first one.
+    fn record_err(&mut self, ty: ErrorType, note: Option::<String>, line: &Tokens, bad_idx: usize) {
+        let file = self.current_file();
+        let line_no = self.current_line_no();
+        self.errors.push(Error::at(ty, note, file, line_no, line, bad_idx));
+    }
+
+    // Unexpected First Token error
     #[inline]
-    fn check_token_pos(&mut self, pos: usize, token: Option::<&'a Token<'a>>) {
-        if pos > 1 {
-            self.err_token = token;
-            self.report_err(Error::new(ErrorType::ExpectedOnlyOneTokenOnTheLeftSide, None))
+    fn uft_err(&mut self, line: &'a Tokens) {
+        self.record_err(ErrorType::UnexpectedToken, None, line, 0);
+    }
+
+    // Parses the tokens between a job's target and its colon as a recipe parameter list, e.g.
+    // `version` and `env`/`"staging"` in `build version:` / `deploy env="staging":`. Bare names
+    // are parameters without a default; `name=value` gives one.
+    fn parse_params(&mut self, tokens: &'a [Token<'a>], line: &'a Tokens) -> Option::<Vec::<Param<'a>>> {
+        let mut params = Vec::new();
+        let mut i = 0;
+
+        while i < tokens.len() {
+            let name = &tokens[i];
+            if !matches!(name.typ, TokenType::Literal) {
+                self.record_err(ErrorType::UnexpectedToken, Some("expected a parameter name".to_string()), line, i + 1);
+                return None
+            }
+
+            if matches!(tokens.get(i + 1).map(|t| t.typ), Some(TokenType::Equal)) {
+                let Some(default) = tokens.get(i + 2) else {
+                    self.record_err(ErrorType::MissingRightHandSide,
+                        Some("parameter is missing its default value".to_string()), line, i + 2);
+                    return None
+                };
+
+                params.push(Param::new(name, Some(default)));
+                i += 3;
+            } else {
+                params.push(Param::new(name, None));
+                i += 1;
+            }
+        }
+
+        Some(params)
+    }
+
+    // Consumes the indented lines that make up a job's body, whether or not its header actually
+    // parsed: a malformed `target params:` line shouldn't leave its body lines behind to be
+    // re-parsed as top-level statements of their own.
+    fn drain_body(&mut self) -> Vec::<&'a Tokens<'a>> {
+        let mut body = Vec::new();
+        while let Some((wc, body_line)) = self.peek_line() {
+            if wc.eq(&0) { break }
+            body.push(body_line);
+            self.next_line();
         }
+        body
     }
 
-    fn parse_eq(first: &'a Token, line: &'a Tokens, eq_idx: usize) -> Item<'a> {
+    fn parse_eq(&mut self, first: &'a Token, line: &'a Tokens, eq_idx: usize) -> Option::<Item<'a>> {
         if let Some(token) = line.get(eq_idx - 1) {
             if token.str.eq("+") {
                 let Some(right_side) = line.get(eq_idx + 1) else {
-                    panic!("Expected right side after expression")
+                    self.record_err(ErrorType::MissingRightHandSide, None, line, eq_idx);
+                    return None
                 };
 
                 let left_side = line.get(eq_idx - 2).unwrap();
                 let expr = Expr::new(left_side, Operation::PlusEqual, right_side);
-                return Item::Expr(expr)
+                return Some(Item::Expr(expr))
             } else if token.str.ends_with("+") {
                 let Some(right_side) = line.get(eq_idx + 1) else {
-                    panic!("Expected right side after expression")
+                    self.record_err(ErrorType::MissingRightHandSide, None, line, eq_idx);
+                    return None
                 };
 
                 let expr = Expr::new(token, Operation::PlusEqual, right_side);
-                return Item::Expr(expr)
+                return Some(Item::Expr(expr))
             } else if token.str.eq("-") {
                 let Some(right_side) = line.get(eq_idx + 2) else {
-                    panic!("Expected right side after expression")
+                    self.record_err(ErrorType::MissingRightHandSide, None, line, eq_idx);
+                    return None
                 };
 
                 let left_side = line.get(eq_idx - 2).unwrap();
                 let expr = Expr::new(left_side, Operation::MinusEqual, right_side);
-                return Item::Expr(expr)
+                return Some(Item::Expr(expr))
             } else if token.str.ends_with("-") {
                 let Some(right_side) = line.get(eq_idx + 1) else {
-                    panic!("Expected right side after expression")
+                    self.record_err(ErrorType::MissingRightHandSide, None, line, eq_idx);
+                    return None
                 };
 
                 let expr = Expr::new(token, Operation::MinusEqual, right_side);
-                return Item::Expr(expr)
+                return Some(Item::Expr(expr))
             }
         }
 
-        // self.check_token_pos(eq_idx, Some(first));
-
         let left_side = first;
         let right_side = line[eq_idx + 1..].into_iter().collect::<Vec::<_>>();
         let decl = Decl::new(left_side, right_side);
-        Item::Decl(decl)
+        Some(Item::Decl(decl))
     }
 
     fn parse_line(&mut self, _: &usize, line: &'a Tokens) {
@@ -170,22 +307,70 @@ impl<'a> Parser<'a> {
         let Some(first) = iter.peek() else { return };
         if first.str.eq("endif") { return };
         match first.typ {
-            Literal => if IFS.contains(&first.str) {
+            Literal => if INCLUDES.contains(&first.str) {
+                let Some(path_token) = iter.nth(1) else {
+                    self.record_err(UnexpectedToken, Some("expected a path after `include`/`import`".to_string()), line, 0);
+                    return
+                };
+
+                let raw = PathBuf::from(path_token.str.trim_matches('"'));
+                let path = if raw.is_relative() {
+                    self.current_dir().join(raw)
+                } else {
+                    raw
+                };
+                let canonical = match path.canonicalize() {
+                    Ok(canonical) => canonical,
+                    Err(_) => {
+                        self.record_err(FailedToInclude, Some(format!("failed to read \"{}\"", path.display())), line, 0);
+                        return
+                    }
+                };
+
+                if self.active.contains(&canonical) {
+                    self.record_err(FailedToInclude,
+                        Some(format!("include cycle: \"{}\" is already being loaded", path.display())), line, 0);
+                    return
+                }
+
+                match self.loader.load(&path) {
+                    Ok(src) => {
+                        let included = linize(src);
+                        // The lexed stream of an included file has to live as long as everything
+                        // else the parser hands out references into (`'a`), but the `Loader`
+                        // only owns the raw source text, not its tokens, so leak the file's
+                        // `LinizedTokens`, same as we'd leak any other per-run arena entry.
+                        let included: &'a LinizedTokens<'a> = Box::leak(Box::new(included));
+                        self.active.push(canonical);
+                        self.frames.push(Frame {
+                            path,
+                            iter: included.into_iter().peekable(),
+                            line_no: 0,
+                        });
+                    }
+                    Err(_) => {
+                        self.record_err(FailedToInclude, Some(format!("failed to read \"{}\"", path.display())), line, 0);
+                    }
+                }
+            } else if IFS.contains(&first.str) {
                 let mut endif = false;
                 let mut body = Vec::new();
                 let (mut else_body, mut else_flag) = (Vec::new(), false);
-                while let Some((_, line)) = self.iter.next() {
-                    if line.iter().find(|t| t.str.eq("else")).is_some() {
+                while let Some((_, if_line)) = self.next_line() {
+                    if if_line.iter().find(|t| t.str.eq("else")).is_some() {
                         else_flag = true;
                     } else {
-                        if line.iter().any(|t| t.str.eq("endif")) {
+                        if if_line.iter().any(|t| t.str.eq("endif")) {
                             endif = true;
                             break
                         }
 
-                        let Some(eq_idx) = line.iter().position(|x| matches!(x.typ, Equal)) else { continue };
-                        let Some(first) = line.get(eq_idx - 1) else { continue };
-                        let item = Self::parse_eq(first, line, eq_idx);
+                        let Some(eq_idx) = if_line.iter().position(|x| matches!(x.typ, Equal)) else { continue };
+                        let Some(if_first) = eq_idx.checked_sub(1).and_then(|i| if_line.get(i)) else {
+                            self.record_err(UnexpectedToken, Some("expected something before `=`".to_string()), if_line, eq_idx);
+                            continue
+                        };
+                        let Some(item) = self.parse_eq(if_first, if_line, eq_idx) else { continue };
                         if else_flag {
                             else_body.push(item);
                         } else {
@@ -195,8 +380,8 @@ impl<'a> Parser<'a> {
                 }
 
                 if !endif {
-                    self.err_token = Some(first);
-                    self.report_err(Error::new(NoClosingEndif, None));
+                    self.record_err(NoClosingEndif, None, line, 0);
+                    return
                 }
 
                 let rev = if first.str.eq("ifeq") { false } else { true };
@@ -205,48 +390,69 @@ impl<'a> Parser<'a> {
                 iter.next();
 
                 let Some(left_side) = iter.next() else {
-                    panic!("If without a left_side")
+                    self.record_err(IfMissingOperand, Some("missing the left-hand side".to_string()), line, 0);
+                    return
                 };
 
                 let Some(right_side) = iter.next() else {
-                    panic!("If without a right_side")
+                    self.record_err(IfMissingOperand, Some("missing the right-hand side".to_string()), line, 1);
+                    return
                 };
 
                 let r#if = If::new(rev, left_side, right_side, body, else_body);
                 self.ast.items.push(Item::If(r#if));
             } else if let Some(eq_idx) = line.iter().position(|x| matches!(x.typ, Equal)) {
-                let item = Self::parse_eq(first, line, eq_idx);
-                self.ast.items.push(item);
+                // A job header with a parameter default, e.g. `deploy env="staging":`, has a `=`
+                // too, but with more than a name (and an optional `+`/`-`) before it and a `:`
+                // after it.
+                let op_before = line[eq_idx - 1].str.eq("+") || line[eq_idx - 1].str.eq("-");
+                let header_colon = line.iter().position(|x| matches!(x.typ, Colon))
+                    .filter(|&colon_idx| eq_idx >= 2 && !op_before && eq_idx < colon_idx);
+                if let Some(colon_idx) = header_colon {
+                    let Some(params) = self.parse_params(&line[1..colon_idx], line) else {
+                        self.drain_body();
+                        return
+                    };
+                    let body = self.drain_body();
+                    let job = Job::new(first, params, &line[colon_idx + 1..], body);
+                    self.ast.items.push(Item::Job(job));
+                } else if let Some(item) = self.parse_eq(first, line, eq_idx) {
+                    self.ast.items.push(item);
+                }
             } else if let Some(colon_idx) = line.iter().position(|x| matches!(x.typ, Colon)) {
-                self.check_token_pos(colon_idx, Some(first));
-
                 let target = first;
+                let Some(params) = self.parse_params(&line[1..colon_idx], line) else {
+                    // The header failed to parse, but its indented body lines are still sitting
+                    // in the stream; drop them here instead of leaving them to be misparsed as
+                    // unrelated top-level statements (and each produce their own bogus error) on
+                    // the next `next_line()`.
+                    self.drain_body();
+                    return
+                };
                 let dependencies = &line[colon_idx + 1..];
-                let mut body = Vec::with_capacity(line.len());
-                while let Some((wc, line)) = self.iter.peek() {
-                    if wc.eq(&0) { break }
-                    body.push(line);
-                    self.iter.next();
-                }
+                let body = self.drain_body();
 
-                let job = Job::new(target, dependencies, body);
+                let job = Job::new(target, params, dependencies, body);
                 self.ast.items.push(Item::Job(job));
             } else {
                 self.uft_err(line);
             },
             Colon => {
-                self.err_token = Some(first);
-                let err = Error::new(JobWithoutTarget, Some("Jobs without targets are not allowed here!"));
-                self.report_err(err);
+                self.record_err(JobWithoutTarget, Some("Jobs without targets are not allowed here!".to_string()), line, 0);
             }
             _ => self.uft_err(line)
         };
     }
 
-    pub fn parse(&mut self) -> Result::<Jobs, crate::parsing::ast::Error> {
-        while let Some((wc, line)) = self.iter.next() {
+    pub fn parse(&mut self) -> Result::<Jobs, ParseError> {
+        while let Some((wc, line)) = self.next_line() {
             self.parse_line(wc, line);
         }
-        self.ast.parse()
+
+        if !self.errors.is_empty() {
+            return Err(ParseError::Syntax(mem::take(&mut self.errors)))
+        }
+
+        self.ast.parse().map_err(ParseError::Ast)
     }
 }
@@ -0,0 +1,16 @@
+mod testsupport;
+
+use testsupport::project;
+
+#[test]
+fn include_pulls_in_a_job_from_another_file() {
+    let proj = project()
+        .file("Buildfile", "include \"lib.bf\"\napp: lib_job\n\ttouch app\n")
+        .file("lib.bf", "lib_job:\n\ttouch lib_job\n")
+        .build();
+
+    proj.run(&["app"])
+        .assert_success()
+        .assert_ran(&["touch lib_job"])
+        .assert_rebuilt("app");
+}
@@ -0,0 +1,25 @@
+mod testsupport;
+
+use testsupport::project;
+
+#[test]
+fn substitutes_a_parameter_default_when_none_is_given() {
+    let proj = project()
+        .file("Buildfile", "deploy env=\"staging\":\n\techo deploying to $env\n")
+        .build();
+
+    proj.run(&["deploy"])
+        .assert_success()
+        .assert_ran(&["echo deploying to staging"]);
+}
+
+#[test]
+fn substitutes_an_overridden_parameter() {
+    let proj = project()
+        .file("Buildfile", "deploy env=\"staging\":\n\techo deploying to $env\n")
+        .build();
+
+    proj.run(&["deploy", "env=prod"])
+        .assert_success()
+        .assert_ran(&["echo deploying to prod"]);
+}
@@ -0,0 +1,14 @@
+mod testsupport;
+
+use testsupport::project;
+
+#[test]
+fn a_malformed_line_produces_a_recoverable_error_instead_of_panicking() {
+    let proj = project()
+        .file("Buildfile", ": foo\n")
+        .build();
+
+    proj.run(&["anything"])
+        .assert_failure()
+        .assert_stderr_contains("[ERROR]");
+}
@@ -0,0 +1,28 @@
+mod testsupport;
+
+use testsupport::project;
+
+#[test]
+fn a_quiet_line_is_not_echoed_but_still_runs() {
+    let proj = project()
+        .file("Buildfile", "app:\n\t@echo hi\n\techo bye\n")
+        .build();
+
+    proj.run(&["app"])
+        .assert_success()
+        .assert_not_ran(&["echo hi"])
+        .assert_ran(&["echo bye"])
+        .assert_stderr_contains("hi\n");
+}
+
+#[test]
+fn the_global_quiet_flag_silences_every_line() {
+    let proj = project()
+        .file("Buildfile", "app:\n\techo hi\n\techo bye\n")
+        .build();
+
+    proj.run(&["--quiet", "app"])
+        .assert_success()
+        .assert_not_ran(&["echo hi"])
+        .assert_not_ran(&["echo bye"]);
+}
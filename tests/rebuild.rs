@@ -0,0 +1,71 @@
+mod testsupport;
+
+use testsupport::project;
+
+#[test]
+fn rebuilds_a_target_whose_dependency_is_newer() {
+    let proj = project()
+        .file("Buildfile", "app: main.c\n\techo building app\n")
+        .file("main.c", "int main(void) { return 0; }\n")
+        .file("app", "")
+        .build();
+    proj.touch("main.c", std::time::Duration::from_secs(2));
+
+    proj.run(&["app"])
+        .assert_success()
+        .assert_ran(&["echo building app"])
+        .assert_rebuilt("app");
+}
+
+#[test]
+fn does_nothing_once_the_target_is_up_to_date() {
+    let proj = project()
+        .file("Buildfile", "app: main.c\n\ttouch app\n")
+        .file("main.c", "int main(void) { return 0; }\n")
+        .build();
+
+    proj.run(&["app"]).assert_success().assert_rebuilt("app");
+    proj.run(&["app"]).assert_success().assert_nothing_to_do_for("app");
+}
+
+#[test]
+fn rebuilds_again_after_the_dependency_is_touched() {
+    let proj = project()
+        .file("Buildfile", "app: main.c\n\ttouch app\n")
+        .file("main.c", "int main(void) { return 0; }\n")
+        .build();
+
+    proj.run(&["app"]).assert_success().assert_rebuilt("app");
+    proj.touch("main.c", std::time::Duration::from_secs(2));
+    proj.run(&["app"]).assert_success().assert_rebuilt("app");
+}
+
+// `compile` is a phony job target, not a file `app` produces, and never writes a file of its
+// own name — exactly the make/just idiom `needs_rebuild` must not try to `stat` as if it were a
+// plain file dependency.
+#[test]
+fn a_job_to_job_dependency_does_not_panic_once_the_target_exists() {
+    let proj = project()
+        .file("Buildfile", "app: compile\n\ttouch app\ncompile:\n\techo compiling\n")
+        .build();
+
+    proj.run(&["app"])
+        .assert_success()
+        .assert_ran(&["echo compiling"])
+        .assert_rebuilt("app");
+
+    proj.run(&["app"])
+        .assert_success()
+        .assert_nothing_to_do_for("app");
+}
+
+#[test]
+fn reports_a_dependency_cycle_instead_of_deadlocking() {
+    let proj = project()
+        .file("Buildfile", "a: b\n\ttouch a\nb: a\n\ttouch b\n")
+        .build();
+
+    proj.run(&["a"])
+        .assert_failure()
+        .assert_stderr_contains("cycle");
+}
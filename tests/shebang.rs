@@ -0,0 +1,14 @@
+mod testsupport;
+
+use testsupport::project;
+
+#[test]
+fn a_shebang_body_runs_as_a_single_script() {
+    let proj = project()
+        .file("Buildfile", "script:\n\t#!/usr/bin/env sh\n\techo from script\n")
+        .build();
+
+    proj.run(&["script"])
+        .assert_success()
+        .assert_ran(&["#!/usr/bin/env sh", "echo from script"]);
+}
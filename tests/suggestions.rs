@@ -0,0 +1,14 @@
+mod testsupport;
+
+use testsupport::project;
+
+#[test]
+fn a_typo_d_target_gets_a_did_you_mean_suggestion() {
+    let proj = project()
+        .file("Buildfile", "build:\n\ttouch build\n")
+        .build();
+
+    proj.run(&["biuld"])
+        .assert_failure()
+        .assert_stderr_contains("Did you mean `build`");
+}
@@ -0,0 +1,15 @@
+mod testsupport;
+
+use testsupport::project;
+
+#[test]
+fn builds_a_non_default_named_target() {
+    let proj = project()
+        .file("Buildfile", "first:\n\ttouch first\nsecond:\n\ttouch second\n")
+        .build();
+
+    proj.run(&["second"])
+        .assert_success()
+        .assert_ran(&["touch second"])
+        .assert_not_ran(&["touch first"]);
+}
@@ -0,0 +1,151 @@
+// A small end-to-end harness modeled on cargo's own `cargo-test-support`: write a throwaway
+// project to a temp directory, run the built `buildfile` binary against it as a real
+// subprocess, then assert on what it printed. Going through a subprocess (rather than calling
+// `Execute` in-process) matters here because failed recipes call `process::exit` directly.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, SystemTime},
+};
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+pub struct ProjectBuilder {
+    root: PathBuf,
+}
+
+pub struct Project {
+    root: PathBuf,
+}
+
+pub struct Run {
+    output: Output,
+}
+
+// Starts a fresh, uniquely-named temp directory for a test project.
+pub fn project() -> ProjectBuilder {
+    let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+    let root = std::env::temp_dir().join(format!("buildfile-test-{}-{id}", std::process::id()));
+    fs::create_dir_all(&root).expect("failed to create temp project dir");
+    ProjectBuilder { root }
+}
+
+impl ProjectBuilder {
+    // Writes `contents` to `path` (relative to the project root), creating parent directories
+    // as needed.
+    pub fn file(self, path: &str, contents: &str) -> Self {
+        let full = self.root.join(path);
+        if let Some(parent) = full.parent() {
+            fs::create_dir_all(parent).unwrap();
+        }
+        fs::write(full, contents).unwrap();
+        self
+    }
+
+    #[inline]
+    pub fn build(self) -> Project {
+        Project { root: self.root }
+    }
+}
+
+impl Project {
+    #[inline]
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    // Bumps a file's modification time `forward` so tests can deterministically exercise
+    // `needs_rebuild` instead of racing the filesystem's mtime resolution.
+    pub fn touch(&self, path: &str, forward: Duration) {
+        let file = fs::File::open(self.root.join(path)).unwrap();
+        file.set_modified(SystemTime::now() + forward).unwrap();
+    }
+
+    // Runs the built `buildfile` binary with `args` inside the project directory.
+    pub fn run(&self, args: &[&str]) -> Run {
+        let output = Command::new(env!("CARGO_BIN_EXE_buildfile"))
+            .args(args)
+            .current_dir(&self.root)
+            .output()
+            .expect("failed to execute the buildfile binary");
+        Run { output }
+    }
+}
+
+impl Run {
+    #[inline]
+    fn stdout(&self) -> String {
+        String::from_utf8_lossy(&self.output.stdout).into_owned()
+    }
+
+    #[inline]
+    fn stderr(&self) -> String {
+        String::from_utf8_lossy(&self.output.stderr).into_owned()
+    }
+
+    pub fn assert_success(&self) -> &Self {
+        assert!(
+            self.output.status.success(),
+            "expected success, got {:?}\nstderr:\n{}", self.output.status, self.stderr()
+        );
+        self
+    }
+
+    pub fn assert_failure(&self) -> &Self {
+        assert!(
+            !self.output.status.success(),
+            "expected failure, got {:?}\nstdout:\n{}", self.output.status, self.stdout()
+        );
+        self
+    }
+
+    pub fn assert_stderr_contains(&self, needle: &str) -> &Self {
+        let stderr = self.stderr();
+        assert!(stderr.contains(needle), "expected stderr to contain `{needle}`, got:\n{stderr}");
+        self
+    }
+
+    pub fn assert_ran(&self, commands: &[&str]) -> &Self {
+        let stdout = self.stdout();
+        for cmd in commands {
+            assert!(
+                stdout.contains(cmd),
+                "expected command `{cmd}` to have been run, got stdout:\n{stdout}"
+            );
+        }
+        self
+    }
+
+    // The inverse of `assert_ran`: asserts a command line was never echoed to stdout, e.g.
+    // because it was run as a quiet (`@`) recipe line.
+    pub fn assert_not_ran(&self, commands: &[&str]) -> &Self {
+        let stdout = self.stdout();
+        for cmd in commands {
+            assert!(
+                !stdout.contains(cmd),
+                "expected command `{cmd}` not to have been echoed, got stdout:\n{stdout}"
+            );
+        }
+        self
+    }
+
+    pub fn assert_nothing_to_do_for(&self, target: &str) -> &Self {
+        let stdout = self.stdout();
+        let expected = format!("Nothing to do for \"{target}\"");
+        assert!(stdout.contains(&expected), "expected \"{expected}\", got stdout:\n{stdout}");
+        self
+    }
+
+    pub fn assert_rebuilt(&self, target: &str) -> &Self {
+        let stdout = self.stdout();
+        let not_rebuilt = format!("Nothing to do for \"{target}\"");
+        assert!(
+            !stdout.contains(&not_rebuilt),
+            "expected \"{target}\" to be rebuilt, but it was reported as up to date:\n{stdout}"
+        );
+        self
+    }
+}